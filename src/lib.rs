@@ -1,67 +1,174 @@
-use anyhow::anyhow;
 use kitsune::{
     activitypub::Fetcher,
-    cache::{Cache, NoopCache},
+    cache::{Cache, NoopCache, RedisCache},
     config::Configuration,
     mapping::MastodonMapper,
     resolve::PostResolver,
     service::{
-        account::AccountService, oauth2::Oauth2Service, post::PostService,
-        search::NoopSearchService, timeline::TimelineService, user::UserService,
+        account::AccountService,
+        oauth2::Oauth2Service,
+        post::PostService,
+        search::{NoopSearchService, SearchService},
+        timeline::TimelineService,
+        user::UserService,
     },
     state::{Service, Zustand},
     webfinger::Webfinger,
 };
+use kitsune_error::Error;
+use kitsune_search::MeiliSearchService;
+use kitsune_wasm_mrf::MrfService;
 use migration::{Migrator, MigratorTrait};
-use sea_orm::SqlxPostgresConnector;
+use sea_orm::{DatabaseConnection, SqlxPostgresConnector};
+use serde::{de::DeserializeOwned, Serialize};
 use shuttle_secrets::{SecretStore, Secrets};
 use shuttle_service::ShuttleAxum;
 use shuttle_shared_db::Postgres;
 use sqlx::PgPool;
-use std::{num::NonZeroUsize, path::PathBuf, sync::Arc};
+use std::{num::NonZeroUsize, path::PathBuf, sync::Arc, time::Duration};
 use sync_wrapper::SyncWrapper;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
-#[shuttle_service::main]
-async fn axum(#[Postgres] db_conn: PgPool, #[Secrets] secret_store: SecretStore) -> ShuttleAxum {
+/// Default time-to-live for Redis-backed caches, used unless a cache is given
+/// its own TTL at the call site.
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Builds a namespaced cache, backing it with Redis when `redis_url` is set and
+/// falling back to [`NoopCache`] (e.g. for local/hobby deployments without Redis).
+fn build_cache<K, V>(
+    redis_url: &str,
+    namespace: &'static str,
+    ttl: Duration,
+) -> Arc<dyn Cache<K, V> + Send + Sync>
+where
+    K: Send + Sync + ToString + 'static,
+    V: Clone + Send + Sync + Serialize + DeserializeOwned + 'static,
+{
+    if redis_url.is_empty() {
+        Arc::new(NoopCache)
+    } else {
+        Arc::new(RedisCache::new(redis_url, namespace, ttl))
+    }
+}
+
+/// Picks the `sea_orm` connection backing the server.
+///
+/// Shuttle always provisions a Postgres pool for us, but an operator can override
+/// that by pointing `DATABASE_URL` at an explicit `postgres://`/`postgresql://` URL
+/// to use their own Postgres instance instead of the one Shuttle provisions.
+///
+/// `sqlite://` is intentionally rejected rather than silently accepted: the
+/// `migration` crate this server runs against only ships Postgres-dialect schema,
+/// so a SQLite connection would pass here and then fail (or worse, produce a
+/// broken schema) the moment `Migrator::up` hits a Postgres-specific column type.
+/// Real SQLite support needs dialect-aware migrations first, not just a connection.
+async fn db_connection(
+    provisioned_pg_pool: PgPool,
+    database_url: &str,
+) -> Result<DatabaseConnection, Error> {
+    if database_url.starts_with("sqlite://") {
+        return Err(Error::invalid_config(
+            "sqlite:// is not supported yet: the migration crate only ships Postgres-dialect schema",
+        ));
+    }
+
+    if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        return sea_orm::Database::connect(database_url)
+            .await
+            .map_err(Error::from);
+    }
+
+    Ok(SqlxPostgresConnector::from_sqlx_postgres_pool(
+        provisioned_pg_pool,
+    ))
+}
+
+/// Wraps an error from a dependency `kitsune_error` has no dedicated `From` impl for
+/// (service builders, `kitsune_wasm_mrf`, `kitsune_search`) into the 500-category
+/// catch-all, instead of assuming a conversion exists that was never asked for.
+fn internal_err(err: impl std::error::Error) -> Error {
+    Error::internal(err.to_string())
+}
+
+/// Builds the shared application state, surfacing every fallible step through
+/// [`kitsune_error::Error`] instead of ad-hoc `anyhow` conversions at each call site.
+async fn build_state(db_conn: PgPool, secret_store: &SecretStore) -> Result<Zustand, Error> {
+    let database_url = secret_store.get("DATABASE_URL").unwrap_or_default();
     let config = Configuration {
-        database_url: String::new(),
+        database_url: database_url.clone(),
         domain: secret_store
             .get("DOMAIN")
-            .ok_or_else(|| anyhow!("Domain not set"))?,
+            .ok_or_else(|| Error::invalid_config("Domain not set"))?,
         frontend_dir: PathBuf::new(),
         job_workers: NonZeroUsize::new(5).unwrap(),
+        mrf_module_dir: secret_store
+            .get("MRF_MODULE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("mrf-modules")),
         port: 0,
-        redis_url: String::new(),
+        redis_url: secret_store.get("REDIS_URL").unwrap_or_default(),
         prometheus_port: 0,
-        search_index_server: String::new(),
-        search_servers: vec![],
+        search_index_server: secret_store.get("SEARCH_INDEX_SERVER").unwrap_or_default(),
+        search_servers: secret_store
+            .get("SEARCH_SERVERS")
+            .filter(|servers| !servers.trim().is_empty())
+            .map(|servers| {
+                servers
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|server| !server.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
         upload_dir: PathBuf::from("uploads"),
     };
 
-    let db_conn = SqlxPostgresConnector::from_sqlx_postgres_pool(db_conn);
-    Migrator::up(&db_conn, None)
-        .await
-        .map_err(anyhow::Error::from)?;
+    let db_conn = db_connection(db_conn, &database_url).await?;
+    Migrator::up(&db_conn, None).await.map_err(Error::from)?;
 
-    let search_service = Arc::new(NoopSearchService);
+    let search_service: Arc<dyn SearchService + Send + Sync> = if config.search_servers.is_empty()
+    {
+        Arc::new(NoopSearchService)
+    } else if config.search_index_server.trim().is_empty() {
+        return Err(Error::invalid_config(
+            "SEARCH_SERVERS is set but SEARCH_INDEX_SERVER is blank",
+        ));
+    } else {
+        Arc::new(
+            MeiliSearchService::new(&config.search_index_server, &config.search_servers)
+                .await
+                .map_err(internal_err)?,
+        )
+    };
+    // MRF is opt-in: only load the module directory if an operator actually
+    // created one, so existing deployments keep booting without it.
+    let mrf_service = if config.mrf_module_dir.is_dir() {
+        MrfService::from_directory(&config.mrf_module_dir)
+            .await
+            .map_err(internal_err)?
+    } else {
+        MrfService::noop()
+    };
 
     let fetcher: Fetcher = Fetcher::new(
         db_conn.clone(),
         search_service.clone(),
-        Arc::new(NoopCache),
-        Arc::new(NoopCache),
+        mrf_service,
+        build_cache(&config.redis_url, "fetcher-object", DEFAULT_CACHE_TTL),
+        build_cache(&config.redis_url, "fetcher-actor", DEFAULT_CACHE_TTL),
     );
-    let webfinger = Webfinger::new(Arc::new(NoopCache) as Arc<dyn Cache<_, _> + Send + Sync>);
+    let webfinger = Webfinger::new(build_cache(&config.redis_url, "webfinger", DEFAULT_CACHE_TTL));
 
     let account_service = AccountService::builder()
         .db_conn(db_conn.clone())
         .build()
-        .map_err(anyhow::Error::from)?;
-
+        .map_err(internal_err)?;
     let oauth2_service = Oauth2Service::builder()
         .db_conn(db_conn.clone())
         .build()
-        .map_err(anyhow::Error::from)?;
+        .map_err(internal_err)?;
 
     let post_resolver = PostResolver::new(db_conn.clone(), fetcher.clone(), webfinger.clone());
     let post_service = PostService::builder()
@@ -70,27 +177,31 @@ async fn axum(#[Postgres] db_conn: PgPool, #[Secrets] secret_store: SecretStore)
         .post_resolver(post_resolver)
         .search_service(search_service.clone())
         .build()
-        .map_err(anyhow::Error::from)?;
+        .map_err(internal_err)?;
 
     let timeline_service = TimelineService::builder()
         .db_conn(db_conn.clone())
         .build()
-        .map_err(anyhow::Error::from)?;
+        .map_err(internal_err)?;
 
     let user_service = UserService::builder()
         .config(config.clone())
         .db_conn(db_conn.clone())
         .build()
-        .map_err(anyhow::Error::from)?;
+        .map_err(internal_err)?;
 
     let mastodon_mapper = MastodonMapper::builder()
         .db_conn(db_conn.clone())
-        .mastodon_cache(Arc::new(NoopCache))
+        .mastodon_cache(build_cache(
+            &config.redis_url,
+            "mastodon-mapper",
+            DEFAULT_CACHE_TTL,
+        ))
         .build()
-        .map_err(anyhow::Error::from)?;
+        .map_err(internal_err)?;
 
-    let state = Zustand {
-        config: config.clone(),
+    Ok(Zustand {
+        config,
         db_conn,
         fetcher,
         mastodon_mapper,
@@ -103,13 +214,23 @@ async fn axum(#[Postgres] db_conn: PgPool, #[Secrets] secret_store: SecretStore)
             user: user_service,
         },
         webfinger,
-    };
+    })
+}
+
+#[shuttle_service::main]
+async fn axum(#[Postgres] db_conn: PgPool, #[Secrets] secret_store: SecretStore) -> ShuttleAxum {
+    let state = build_state(db_conn, &secret_store)
+        .await
+        .map_err(anyhow::Error::from)?;
 
-    for _ in 0..config.job_workers.get() {
+    for _ in 0..state.config.job_workers.get() {
         tokio::spawn(kitsune::job::run(state.clone()));
     }
 
-    let router = kitsune::http::router(state);
+    let router = kitsune::http::router(state).merge(
+        SwaggerUi::new("/api-docs/swagger-ui")
+            .url("/api-docs/openapi.json", kitsune::http::openapi::ApiDoc::openapi()),
+    );
     let sync_wrapper = SyncWrapper::new(router);
 
     Ok(sync_wrapper)